@@ -0,0 +1,14 @@
+//! Git blame/history data returned by `AbstractServer::fetch_file_blame` and
+//! `fetch_file_at_rev`.
+
+use serde::{Deserialize, Serialize};
+
+/// The attribution for a single line of a file, as of a blame walk rooted at
+/// some revision.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BlameLine {
+    pub rev: String,
+    pub author: String,
+    pub date: String,
+    pub prev_path: Option<String>,
+}
@@ -0,0 +1,47 @@
+//! Capability/version negotiation between pipeline clients and whichever
+//! `AbstractServer` backend a pipeline ends up talking to.
+//!
+//! A pipeline is built purely from command-line flags, so by the time we
+//! know which commands the user actually wants to run we may already be
+//! talking to a backend that doesn't implement all of them -- most commonly
+//! an older remote searchfox web server being driven by a newer `tools`
+//! client.  `AbstractServer::capabilities()` lets `build_pipeline` check
+//! that up front instead of failing opaquely partway through a command.
+//!
+//! `LocalIndex` reports a static list, since it's always built against the
+//! same code as the client. `RemoteServer` -- the motivating scenario, a
+//! newer client talking to an older remote searchfox web server -- instead
+//! populates its `ServerCapabilities` from a `/version` document it fetches
+//! when constructed, reporting whatever protocol version and capability
+//! list that older server actually speaks.
+
+use serde::{Deserialize, Serialize};
+
+/// Bumped whenever the wire contract between a pipeline client and a remote
+/// searchfox web server changes in a way older servers can't handle.
+pub const CURRENT_PROTOCOL_VERSION: u32 = 1;
+
+/// One discrete piece of `AbstractServer` functionality a backend may or may
+/// not implement.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Capability {
+    CrossrefLookup,
+    SearchIdentifiers,
+    PerformQuery,
+    Blame,
+}
+
+/// What a backend supports: the protocol revision it speaks, and which
+/// individual capabilities it implements.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServerCapabilities {
+    pub protocol_version: u32,
+    pub supported: Vec<Capability>,
+}
+
+impl ServerCapabilities {
+    pub fn supports(&self, cap: Capability) -> bool {
+        self.supported.contains(&cap)
+    }
+}
@@ -1,52 +1,112 @@
+use std::path::Path;
+
 use async_trait::async_trait;
-use flate2::read::GzDecoder;
 use futures_core::stream::BoxStream;
-use serde_json::{from_str, Value};
-use std::io::Read;
-use tokio::fs::File;
-use tokio::io::AsyncReadExt;
+use git2::{BlameOptions, Repository};
+use serde_json::Value;
+use tokio::io::{AsyncReadExt, BufReader};
+use tokio::task;
 
+use super::blame::BlameLine;
+use super::capabilities::{Capability, ServerCapabilities, CURRENT_PROTOCOL_VERSION};
+use super::ndjson_stream::stream_ndjson_lines;
 use super::server_interface::{AbstractServer, ErrorDetails, ErrorLayer, Result, ServerError};
 
 use crate::config::{load, TreeConfigPaths};
+use crate::file_format::codec;
 use crate::file_format::crossref_lookup::CrossrefLookupMap;
 use crate::file_format::identifiers::IdentMap;
 
 /// IO errors amount to a 404 for our purposes which means a sticky problem.
 impl From<std::io::Error> for ServerError {
     fn from(err: std::io::Error) -> ServerError {
-        ServerError::StickyProblem(ErrorDetails {
-            layer: ErrorLayer::ServerLayer,
-            message: err.to_string(),
-        })
+        ServerError::StickyProblem(ErrorDetails::new(ErrorLayer::ServerLayer, err.to_string()))
     }
 }
 
-/// Read newline-delimited JSON that's been gzip-compressed.
-async fn read_gzipped_ndjson_from_file(path: &str) -> Result<Vec<Value>> {
-    let mut f = File::open(path).await?;
-    // We read the entirety to a buffer because
-    // https://github.com/serde-rs/json/issues/160 suggests that the buffered
-    // reader performance is likely to be much worse.
-    //
-    // When we want to go async here,
-    // https://github.com/rust-lang/flate2-rs/pull/214 suggests that we want to
-    // use the `async-compression` crate.
-    let mut buffer = Vec::new();
-    f.read_to_end(&mut buffer).await?;
+/// Same deal for `git2`: anything that goes wrong opening the repo, resolving
+/// a revision, or walking a blame is a 404-shaped problem for our purposes.
+impl From<git2::Error> for ServerError {
+    fn from(err: git2::Error) -> ServerError {
+        ServerError::StickyProblem(ErrorDetails::new(ErrorLayer::ServerLayer, err.to_string()))
+    }
+}
 
-    let mut gz = GzDecoder::new(&buffer[..]);
+/// Blame `sf_path` as of `rev`.  `git2::Repository` isn't `Sync`, so we can't
+/// hold one on `LocalIndex` across await points; instead we open it fresh
+/// inside `spawn_blocking`, the same model `build-blame.rs` already uses for
+/// its own compute threads, so the non-`Sync` handle never has to cross an
+/// await.
+fn blame_file_blocking(git_path: String, sf_path: String, rev: String) -> Result<Vec<BlameLine>> {
+    let repo = Repository::open(&git_path)?;
+    let commit = repo.revparse_single(&rev)?.peel_to_commit()?;
 
-    let mut raw_str = String::new();
-    gz.read_to_string(&mut raw_str)?;
+    let mut opts = BlameOptions::new();
+    opts.newest_commit(commit.id());
 
-    // let mut raw_str = String::new();
-    // f.read_to_string(&mut raw_str).await?;
+    let blame = repo.blame_file(Path::new(&sf_path), Some(&mut opts))?;
+
+    let mut lines = vec![];
+    for hunk in blame.iter() {
+        let hunk_commit = repo.find_commit(hunk.final_commit_id())?;
+        let rev = hunk_commit.id().to_string();
+        let author = hunk_commit.author().name().unwrap_or("").to_string();
+        let date = hunk_commit.time().seconds().to_string();
+        let prev_path = hunk.orig_path().map(|p| git_relative_path_to_sf_path(p));
+
+        for _ in 0..hunk.lines_in_hunk() {
+            lines.push(BlameLine {
+                rev: rev.clone(),
+                author: author.clone(),
+                date: date.clone(),
+                prev_path: prev_path.clone(),
+            });
+        }
+    }
 
-    raw_str
-        .lines()
-        .map(|s| from_str(s).map_err(|e| ServerError::from(e)))
-        .collect()
+    Ok(lines)
+}
+
+/// Translate a path as `git2` reports it (relative to the repository root)
+/// into searchfox-path space. For `LocalIndex` the two coincide: `sf_path`s
+/// are already relative to `git_path`, the same assumption
+/// `file_at_rev_blocking` below makes when it looks `sf_path` up directly in
+/// the commit's tree. If a tree's indexed root and git checkout root ever
+/// diverge (e.g. indexing a subdirectory of a larger git repository), this
+/// is the one place that would need to learn the prefix between them.
+fn git_relative_path_to_sf_path(git_relative_path: &Path) -> String {
+    git_relative_path.to_string_lossy().into_owned()
+}
+
+/// Fetch the contents of `sf_path` as of `rev`, again opening the repository
+/// fresh inside `spawn_blocking` for the same non-`Sync` reason as
+/// `blame_file_blocking`.
+fn file_at_rev_blocking(git_path: String, sf_path: String, rev: String) -> Result<String> {
+    let repo = Repository::open(&git_path)?;
+    let commit = repo.revparse_single(&rev)?.peel_to_commit()?;
+    let tree = commit.tree()?;
+    let entry = tree.get_path(Path::new(&sf_path))?;
+    let blob = entry.to_object(&repo)?.peel_to_blob()?;
+    String::from_utf8(blob.content().to_vec()).map_err(|e| {
+        ServerError::StickyProblem(ErrorDetails::new(
+            ErrorLayer::ServerLayer,
+            format!("{}@{}: not valid utf-8: {}", sf_path, rev, e),
+        ))
+    })
+}
+
+/// Open a (possibly compressed) newline-delimited JSON file and expose it as
+/// a lazy stream of parsed records, decompressing as we go rather than
+/// materializing the whole file in memory.
+///
+/// The codec (gzip, zstd, bzip2, or none) is sniffed from the file's magic
+/// bytes by [`codec::open_decoded`], so this doesn't care what compressed
+/// it; framing the decoded bytes into records and reporting parse errors is
+/// handled by the backend-agnostic [`stream_ndjson_lines`].
+async fn stream_ndjson_from_file(path: &str) -> Result<BoxStream<'static, Result<Value>>> {
+    let decoded = codec::open_decoded(path).await?;
+    let reader = BufReader::new(decoded);
+    Ok(stream_ndjson_lines(reader, path.to_string()))
 }
 
 #[allow(dead_code)]
@@ -72,40 +132,41 @@ struct LocalIndex {
 
 #[async_trait]
 impl AbstractServer for LocalIndex {
+    fn capabilities(&self) -> ServerCapabilities {
+        // Local servers are built from the same code as the client, so their
+        // capability set is just whatever we've actually implemented here --
+        // notably, `perform_query` isn't one of them yet (see the `TODO`
+        // below), so we don't claim it.
+        ServerCapabilities {
+            protocol_version: CURRENT_PROTOCOL_VERSION,
+            supported: vec![
+                Capability::CrossrefLookup,
+                Capability::SearchIdentifiers,
+                Capability::Blame,
+            ],
+        }
+    }
+
     fn translate_analysis_path(&self, sf_path: &str) -> Result<String> {
-        Ok(format!(
-            "{}/analysis/{}.gz",
-            self.config_paths.index_path, sf_path
-        ))
+        let base = format!("{}/analysis/{}", self.config_paths.index_path, sf_path);
+        // Fall back to the historical `.gz` name when none of the codec
+        // variants exist yet, so the path we report is still a sensible one
+        // to surface in a "not found" error.
+        Ok(codec::probe_existing_path(&base).unwrap_or_else(|| format!("{}.gz", base)))
     }
 
-    async fn fetch_raw_analysis(&self, sf_path: &str) -> Result<BoxStream<Value>> {
-        let full_path = format!("{}/analysis/{}.gz", self.config_paths.index_path, sf_path);
-        let values = read_gzipped_ndjson_from_file(&full_path).await?;
-        Ok(Box::pin(tokio_stream::iter(values)))
+    async fn fetch_raw_analysis(&self, sf_path: &str) -> Result<BoxStream<'static, Result<Value>>> {
+        let full_path = self.translate_analysis_path(sf_path)?;
+        stream_ndjson_from_file(&full_path).await
     }
 
     async fn fetch_html(&self, sf_path: &str) -> Result<String> {
-        let full_path = format!("{}/file/{}.gz", self.config_paths.index_path, sf_path);
+        let base = format!("{}/file/{}", self.config_paths.index_path, sf_path);
+        let full_path = codec::probe_existing_path(&base).unwrap_or_else(|| format!("{}.gz", base));
 
-        // If we were dealing with uncompressed files.
-        /*
-        let mut f = File::open(full_path).await?;
+        let mut decoded = codec::open_decoded(&full_path).await?;
         let mut raw_str = String::new();
-        f.read_to_string(&mut raw_str).await?;
-        */
-
-        let mut f = File::open(full_path).await?;
-        let mut buffer = Vec::new();
-        f.read_to_end(&mut buffer).await?;
-
-        // When we want to go async here,
-        // https://github.com/rust-lang/flate2-rs/pull/214 suggests that we want
-        // to use the `async-compression` crate.
-        let mut gz = GzDecoder::new(&buffer[..]);
-
-        let mut raw_str = String::new();
-        gz.read_to_string(&mut raw_str)?;
+        decoded.read_to_string(&mut raw_str).await?;
 
         Ok(raw_str)
     }
@@ -141,6 +202,46 @@ impl AbstractServer for LocalIndex {
         // infrastructure...
         Err(ServerError::Unsupported)
     }
+
+    async fn fetch_file_blame(&self, sf_path: &str, rev: &str) -> Result<Vec<BlameLine>> {
+        let git_path = self.git_path()?;
+        let sf_path = sf_path.to_string();
+        let rev = rev.to_string();
+        run_git_blocking(move || blame_file_blocking(git_path, sf_path, rev)).await
+    }
+
+    async fn fetch_file_at_rev(&self, sf_path: &str, rev: &str) -> Result<String> {
+        let git_path = self.git_path()?;
+        let sf_path = sf_path.to_string();
+        let rev = rev.to_string();
+        run_git_blocking(move || file_at_rev_blocking(git_path, sf_path, rev)).await
+    }
+}
+
+impl LocalIndex {
+    /// The path to this tree's git repository, or a `ServerError` if the
+    /// tree wasn't configured with one.
+    fn git_path(&self) -> Result<String> {
+        self.config_paths.git_path.clone().ok_or_else(|| {
+            ServerError::StickyProblem(ErrorDetails::new(
+                ErrorLayer::ServerLayer,
+                format!("tree {} has no git repository configured", self.tree_name),
+            ))
+        })
+    }
+}
+
+/// Run a blocking git2-based closure on the blocking thread pool, mapping a
+/// `JoinError` (panic/cancellation) to the same `ServerError` shape the
+/// closure's own errors already use.
+async fn run_git_blocking<T, F>(f: F) -> Result<T>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Result<T> + Send + 'static,
+{
+    task::spawn_blocking(f).await.map_err(|e| {
+        ServerError::StickyProblem(ErrorDetails::new(ErrorLayer::ServerLayer, e.to_string()))
+    })?
 }
 
 pub fn make_local_server(
@@ -151,10 +252,10 @@ pub fn make_local_server(
     let tree_config = match config.trees.remove(&tree_name.to_string()) {
         Some(t) => t,
         None => {
-            return Err(ServerError::StickyProblem(ErrorDetails {
-                layer: ErrorLayer::BadInput,
-                message: format!("bad tree name: {}", &tree_name),
-            }))
+            return Err(ServerError::StickyProblem(ErrorDetails::new(
+                ErrorLayer::BadInput,
+                format!("bad tree name: {}", &tree_name),
+            )))
         }
     };
 
@@ -0,0 +1,386 @@
+//! A `MuxServer` fans a single logical query out across several underlying
+//! `AbstractServer` backends -- any mix of `LocalIndex` and remote servers --
+//! so a query can span, say, mozilla-central plus a local work-tree index in
+//! one go.
+//!
+//! Lookups that can meaningfully be merged (`crossref_lookup`,
+//! `search_identifiers`) are sent to every backend concurrently and their
+//! results combined.  Lookups that resolve to a single artifact
+//! (`fetch_raw_analysis`, `fetch_html`, `perform_query`, `fetch_file_blame`,
+//! `fetch_file_at_rev`) are instead tried against backends in priority
+//! order, since only one of them is going to have the answer: any error from
+//! one backend -- whether `StickyProblem` (our stand-in for "404") or
+//! `Unsupported` (this backend doesn't implement the call at all) -- just
+//! means try the next one. `capabilities()` reports a capability as
+//! supported as soon as any one backend has it, so a per-backend
+//! `Unsupported` has to be survivable here for that contract to hold.
+
+use async_trait::async_trait;
+use futures_core::stream::BoxStream;
+use futures_util::future::join_all;
+use serde_json::{Map, Value};
+
+use super::blame::BlameLine;
+use super::capabilities::{Capability, ServerCapabilities};
+use super::server_interface::{AbstractServer, Result, ServerError};
+
+pub struct MuxServer {
+    /// Backends in priority order; `fetch_raw_analysis`/`fetch_html` try them
+    /// in this order and return the first hit.
+    backends: Vec<Box<dyn AbstractServer + Send + Sync>>,
+}
+
+impl MuxServer {
+    pub fn new(backends: Vec<Box<dyn AbstractServer + Send + Sync>>) -> MuxServer {
+        MuxServer { backends }
+    }
+}
+
+/// Merge a set of crossref lookup results into one, deduping by merging
+/// their JSON object fields.  Backends that returned `Value::Null` (the
+/// convention for "no crossref data") are simply skipped.
+fn merge_crossref_values(values: Vec<Value>) -> Value {
+    let mut merged = Map::new();
+    for value in values {
+        if let Value::Object(obj) = value {
+            for (key, val) in obj {
+                merged
+                    .entry(key)
+                    .and_modify(|existing| merge_json_value(existing, &val))
+                    .or_insert(val);
+            }
+        }
+    }
+    if merged.is_empty() {
+        Value::Null
+    } else {
+        Value::Object(merged)
+    }
+}
+
+/// Merge `incoming` into `existing` in place: arrays are concatenated and
+/// deduped, objects are merged key-by-key, anything else is left as-is
+/// (first backend to report a scalar wins).
+fn merge_json_value(existing: &mut Value, incoming: &Value) {
+    match (existing, incoming) {
+        (Value::Array(existing_arr), Value::Array(incoming_arr)) => {
+            for item in incoming_arr {
+                if !existing_arr.contains(item) {
+                    existing_arr.push(item.clone());
+                }
+            }
+        }
+        (Value::Object(existing_obj), Value::Object(incoming_obj)) => {
+            for (key, val) in incoming_obj {
+                existing_obj
+                    .entry(key.clone())
+                    .and_modify(|e| merge_json_value(e, val))
+                    .or_insert_with(|| val.clone());
+            }
+        }
+        _ => {}
+    }
+}
+
+#[async_trait]
+impl AbstractServer for MuxServer {
+    fn capabilities(&self) -> ServerCapabilities {
+        // Conservative on protocol version (the oldest backend sets the
+        // floor on what we can safely assume), permissive on capabilities
+        // (a feature works as long as at least one backend implements it --
+        // the other backends just won't contribute to that query).
+        let protocol_version = self
+            .backends
+            .iter()
+            .map(|b| b.capabilities().protocol_version)
+            .min()
+            .unwrap_or(0);
+
+        let mut supported = vec![];
+        for cap in [
+            Capability::CrossrefLookup,
+            Capability::SearchIdentifiers,
+            Capability::PerformQuery,
+            Capability::Blame,
+        ] {
+            if self.backends.iter().any(|b| b.capabilities().supports(cap)) {
+                supported.push(cap);
+            }
+        }
+
+        ServerCapabilities {
+            protocol_version,
+            supported,
+        }
+    }
+
+    fn translate_analysis_path(&self, sf_path: &str) -> Result<String> {
+        // Since this is a synchronous call we don't probe which backend
+        // actually has the path; the first backend in priority order
+        // translates it, whether or not it has the file.
+        self.backends
+            .first()
+            .ok_or(ServerError::Unsupported)?
+            .translate_analysis_path(sf_path)
+    }
+
+    async fn fetch_raw_analysis(&self, sf_path: &str) -> Result<BoxStream<'static, Result<Value>>> {
+        let mut last_err = ServerError::Unsupported;
+        for backend in &self.backends {
+            match backend.fetch_raw_analysis(sf_path).await {
+                Ok(stream) => return Ok(stream),
+                Err(err) => last_err = err,
+            }
+        }
+        Err(last_err)
+    }
+
+    async fn fetch_html(&self, sf_path: &str) -> Result<String> {
+        let mut last_err = ServerError::Unsupported;
+        for backend in &self.backends {
+            match backend.fetch_html(sf_path).await {
+                Ok(html) => return Ok(html),
+                Err(err) => last_err = err,
+            }
+        }
+        Err(last_err)
+    }
+
+    async fn crossref_lookup(&self, symbol: &str) -> Result<Value> {
+        let futures = self.backends.iter().map(|b| b.crossref_lookup(symbol));
+        let results = join_all(futures).await;
+
+        let mut values = vec![];
+        for result in results {
+            match result {
+                Ok(value) => values.push(value),
+                Err(_) => {}
+            }
+        }
+
+        Ok(merge_crossref_values(values))
+    }
+
+    async fn search_identifiers(
+        &self,
+        needle: &str,
+        exact_match: bool,
+        ignore_case: bool,
+        match_limit: usize,
+    ) -> Result<Vec<(String, String)>> {
+        let futures = self
+            .backends
+            .iter()
+            .map(|b| b.search_identifiers(needle, exact_match, ignore_case, match_limit));
+        let results = join_all(futures).await;
+
+        let mut per_backend = vec![];
+        for result in results {
+            match result {
+                Ok(results) => per_backend.push(results.into_iter()),
+                Err(_) => per_backend.push(vec![].into_iter()),
+            }
+        }
+
+        // Round-robin across backends so no single one can starve the
+        // others out of the combined `match_limit`.
+        let mut merged = vec![];
+        'outer: while merged.len() < match_limit {
+            let mut any_progress = false;
+            for iter in per_backend.iter_mut() {
+                if merged.len() == match_limit {
+                    break 'outer;
+                }
+                if let Some(item) = iter.next() {
+                    any_progress = true;
+                    if !merged.contains(&item) {
+                        merged.push(item);
+                    }
+                }
+            }
+            if !any_progress {
+                break;
+            }
+        }
+
+        Ok(merged)
+    }
+
+    async fn perform_query(&self, q: &str) -> Result<Value> {
+        let mut last_err = ServerError::Unsupported;
+        for backend in &self.backends {
+            match backend.perform_query(q).await {
+                Ok(value) => return Ok(value),
+                Err(err) => last_err = err,
+            }
+        }
+        Err(last_err)
+    }
+
+    async fn fetch_file_blame(&self, sf_path: &str, rev: &str) -> Result<Vec<BlameLine>> {
+        let mut last_err = ServerError::Unsupported;
+        for backend in &self.backends {
+            match backend.fetch_file_blame(sf_path, rev).await {
+                Ok(lines) => return Ok(lines),
+                Err(err) => last_err = err,
+            }
+        }
+        Err(last_err)
+    }
+
+    async fn fetch_file_at_rev(&self, sf_path: &str, rev: &str) -> Result<String> {
+        let mut last_err = ServerError::Unsupported;
+        for backend in &self.backends {
+            match backend.fetch_file_at_rev(sf_path, rev).await {
+                Ok(content) => return Ok(content),
+                Err(err) => last_err = err,
+            }
+        }
+        Err(last_err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use async_trait::async_trait;
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn merge_json_value_concatenates_arrays_without_duplicates() {
+        let mut existing = json!(["a", "b"]);
+        merge_json_value(&mut existing, &json!(["b", "c"]));
+        assert_eq!(existing, json!(["a", "b", "c"]));
+    }
+
+    #[test]
+    fn merge_json_value_merges_objects_key_by_key() {
+        let mut existing = json!({"uses": ["a"], "defs": ["x"]});
+        merge_json_value(&mut existing, &json!({"uses": ["b"], "decls": ["y"]}));
+        assert_eq!(
+            existing,
+            json!({"uses": ["a", "b"], "defs": ["x"], "decls": ["y"]})
+        );
+    }
+
+    #[test]
+    fn merge_crossref_values_skips_null_backends_and_merges_the_rest() {
+        let merged = merge_crossref_values(vec![
+            Value::Null,
+            json!({"uses": ["a"]}),
+            json!({"uses": ["b"]}),
+        ]);
+        assert_eq!(merged, json!({"uses": ["a", "b"]}));
+    }
+
+    #[test]
+    fn merge_crossref_values_of_all_nulls_is_null() {
+        assert_eq!(merge_crossref_values(vec![Value::Null, Value::Null]), Value::Null);
+    }
+
+    /// A stub backend that only implements `search_identifiers`, returning
+    /// a fixed list; every other method is unreachable for these tests.
+    struct StubBackend {
+        results: Vec<(String, String)>,
+    }
+
+    #[async_trait]
+    impl AbstractServer for StubBackend {
+        fn capabilities(&self) -> ServerCapabilities {
+            unimplemented!()
+        }
+
+        fn translate_analysis_path(&self, _sf_path: &str) -> Result<String> {
+            unimplemented!()
+        }
+
+        async fn fetch_raw_analysis(
+            &self,
+            _sf_path: &str,
+        ) -> Result<BoxStream<'static, Result<Value>>> {
+            unimplemented!()
+        }
+
+        async fn fetch_html(&self, _sf_path: &str) -> Result<String> {
+            unimplemented!()
+        }
+
+        async fn crossref_lookup(&self, _symbol: &str) -> Result<Value> {
+            unimplemented!()
+        }
+
+        async fn search_identifiers(
+            &self,
+            _needle: &str,
+            _exact_match: bool,
+            _ignore_case: bool,
+            _match_limit: usize,
+        ) -> Result<Vec<(String, String)>> {
+            Ok(self.results.clone())
+        }
+
+        async fn perform_query(&self, _q: &str) -> Result<Value> {
+            unimplemented!()
+        }
+
+        async fn fetch_file_blame(&self, _sf_path: &str, _rev: &str) -> Result<Vec<BlameLine>> {
+            unimplemented!()
+        }
+
+        async fn fetch_file_at_rev(&self, _sf_path: &str, _rev: &str) -> Result<String> {
+            unimplemented!()
+        }
+    }
+
+    fn stub(results: &[(&str, &str)]) -> Box<dyn AbstractServer + Send + Sync> {
+        Box::new(StubBackend {
+            results: results
+                .iter()
+                .map(|(sym, id)| (sym.to_string(), id.to_string()))
+                .collect(),
+        })
+    }
+
+    #[tokio::test]
+    async fn search_identifiers_round_robins_across_backends_up_to_match_limit() {
+        let mux = MuxServer::new(vec![
+            stub(&[("a", "1"), ("b", "2")]),
+            stub(&[("c", "3"), ("d", "4")]),
+        ]);
+        let results = mux
+            .search_identifiers("needle", false, false, 3)
+            .await
+            .unwrap();
+        // One from each backend, then back to the first, rather than
+        // draining the first backend before touching the second.
+        assert_eq!(
+            results,
+            vec![
+                ("a".to_string(), "1".to_string()),
+                ("c".to_string(), "3".to_string()),
+                ("b".to_string(), "2".to_string()),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn search_identifiers_dedups_identical_results_across_backends() {
+        let mux = MuxServer::new(vec![stub(&[("a", "1")]), stub(&[("a", "1")])]);
+        let results = mux
+            .search_identifiers("needle", false, false, 10)
+            .await
+            .unwrap();
+        assert_eq!(results, vec![("a".to_string(), "1".to_string())]);
+    }
+
+    #[tokio::test]
+    async fn search_identifiers_with_zero_match_limit_returns_nothing() {
+        let mux = MuxServer::new(vec![stub(&[("a", "1"), ("b", "2")])]);
+        let results = mux
+            .search_identifiers("needle", false, false, 0)
+            .await
+            .unwrap();
+        assert!(results.is_empty());
+    }
+}
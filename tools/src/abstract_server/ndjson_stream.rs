@@ -0,0 +1,141 @@
+//! Turning a line-oriented byte stream into a lazy stream of parsed NDJSON
+//! records -- shared by `LocalIndex` (reading a local, possibly-compressed
+//! file) and `RemoteServer` (reading an HTTP response body), so the two
+//! backends don't duplicate record-framing and parse-error bookkeeping.
+
+use futures_core::stream::BoxStream;
+use futures_util::stream;
+use serde_json::{from_str, Value};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt};
+
+use super::server_interface::{ErrorDetails, ErrorLayer, ErrorPosition, Result, ServerError};
+
+/// Read `reader` line-by-line and yield each non-empty line parsed as JSON,
+/// lazily, as a `BoxStream`. `source_label` (a file path or URL) is folded
+/// into parse-error messages, and the byte offset/line number of each
+/// record is attached to the error as structured `ErrorPosition` data. A
+/// final line missing its trailing newline is still parsed, since
+/// `read_until`'s returned byte count is > 0 even without one.
+pub fn stream_ndjson_lines<R>(reader: R, source_label: String) -> BoxStream<'static, Result<Value>>
+where
+    R: AsyncBufRead + Send + Unpin + 'static,
+{
+    let stream = stream::unfold(
+        (reader, source_label, 0u64, 0u64),
+        |(mut reader, source_label, mut offset, mut lineno)| async move {
+            loop {
+                let mut raw_line = Vec::new();
+                let read = match reader.read_until(b'\n', &mut raw_line).await {
+                    Ok(read) => read,
+                    Err(e) => {
+                        return Some((
+                            Err(ServerError::from(e)),
+                            (reader, source_label, offset, lineno),
+                        ))
+                    }
+                };
+                if read == 0 {
+                    return None;
+                }
+                let line_offset = offset;
+                offset += read as u64;
+                lineno += 1;
+
+                while matches!(raw_line.last(), Some(b'\n') | Some(b'\r')) {
+                    raw_line.pop();
+                }
+                if raw_line.is_empty() {
+                    continue;
+                }
+
+                let position = ErrorPosition {
+                    line: lineno,
+                    byte_offset: line_offset,
+                };
+                let parsed = std::str::from_utf8(&raw_line)
+                    .map_err(|e| {
+                        ServerError::StickyProblem(ErrorDetails::with_position(
+                            ErrorLayer::ServerLayer,
+                            format!("{}:{}: invalid utf-8: {}", source_label, lineno, e),
+                            position.clone(),
+                        ))
+                    })
+                    .and_then(|s| {
+                        from_str(s).map_err(|e| {
+                            ServerError::StickyProblem(ErrorDetails::with_position(
+                                ErrorLayer::ServerLayer,
+                                format!("{}:{}: {}", source_label, lineno, e),
+                                position.clone(),
+                            ))
+                        })
+                    });
+
+                return Some((parsed, (reader, source_label, offset, lineno)));
+            }
+        },
+    );
+
+    Box::pin(stream)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use futures_util::StreamExt;
+    use serde_json::json;
+
+    use super::*;
+
+    async fn collect(data: &'static [u8]) -> Vec<Result<Value>> {
+        let reader = Cursor::new(data);
+        stream_ndjson_lines(reader, "test-source".to_string())
+            .collect()
+            .await
+    }
+
+    #[tokio::test]
+    async fn parses_a_truncated_final_line_missing_its_newline() {
+        let results = collect(b"{\"a\":1}\n{\"a\":2}").await;
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].as_ref().unwrap(), &json!({"a": 1}));
+        assert_eq!(results[1].as_ref().unwrap(), &json!({"a": 2}));
+    }
+
+    #[tokio::test]
+    async fn skips_blank_lines() {
+        let results = collect(b"{\"a\":1}\n\n{\"a\":2}\n").await;
+        assert_eq!(results.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn reports_invalid_utf8_with_its_line_and_byte_offset() {
+        let results = collect(b"{\"a\":1}\n\xff\xfe\n").await;
+        assert_eq!(results.len(), 2);
+        assert!(results[0].is_ok());
+        match results[1].as_ref().unwrap_err() {
+            ServerError::StickyProblem(details) => {
+                let position = details.position.as_ref().expect("position");
+                assert_eq!(position.line, 2);
+                assert_eq!(position.byte_offset, 8);
+                assert!(details.message.contains("invalid utf-8"));
+            }
+            other => panic!("expected StickyProblem, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn reports_malformed_json_with_its_line_and_byte_offset() {
+        let results = collect(b"{\"a\":1}\nnot json\n").await;
+        assert_eq!(results.len(), 2);
+        assert!(results[0].is_ok());
+        match results[1].as_ref().unwrap_err() {
+            ServerError::StickyProblem(details) => {
+                let position = details.position.as_ref().expect("position");
+                assert_eq!(position.line, 2);
+                assert_eq!(position.byte_offset, 8);
+            }
+            other => panic!("expected StickyProblem, got {:?}", other),
+        }
+    }
+}
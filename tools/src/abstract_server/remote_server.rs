@@ -0,0 +1,216 @@
+//! `RemoteServer`: an `AbstractServer` backed by a searchfox web server
+//! reached over HTTP, for pipelines pointed at `--server http://...` rather
+//! than a local index.
+//!
+//! On construction it fetches the server's `/version` document and stores
+//! the `ServerCapabilities` it reports -- the handshake `capabilities.rs`
+//! describes -- so `build_pipeline` can reject commands the remote end is
+//! too old to support with a clear message instead of failing opaquely
+//! partway through one.
+
+use std::time::Duration;
+
+use async_trait::async_trait;
+use futures_core::stream::BoxStream;
+use futures_util::TryStreamExt;
+use reqwest::StatusCode;
+use serde_json::Value;
+use tokio_util::io::StreamReader;
+use url::Url;
+
+use super::blame::BlameLine;
+use super::capabilities::ServerCapabilities;
+use super::ndjson_stream::stream_ndjson_lines;
+use super::server_interface::{AbstractServer, ErrorDetails, ErrorLayer, Result, ServerError};
+
+/// Network/transport failures talking to the remote server are 404-shaped
+/// for our purposes, the same treatment `local_index.rs` gives
+/// `std::io::Error`.
+impl From<reqwest::Error> for ServerError {
+    fn from(err: reqwest::Error) -> ServerError {
+        ServerError::StickyProblem(ErrorDetails::new(ErrorLayer::ServerLayer, err.to_string()))
+    }
+}
+
+pub struct RemoteServer {
+    base_url: Url,
+    tree: String,
+    client: reqwest::Client,
+    capabilities: ServerCapabilities,
+}
+
+/// `Url::join` resolves its argument the way a browser resolves a relative
+/// link: against a `base_url` that lacks a trailing slash, the last path
+/// segment is treated as a file name and dropped (`http://host/searchfox`
+/// + `mozilla-central/version` joins to `http://host/mozilla-central/version`,
+/// silently losing `/searchfox`). Force a trailing slash on `base_url`
+/// first so `join` always resolves relative to the full path, the way a
+/// caller who wrote `--server http://host/searchfox` expects.
+fn with_trailing_slash(base_url: &Url) -> Url {
+    if base_url.path().ends_with('/') {
+        base_url.clone()
+    } else {
+        let mut url = base_url.clone();
+        url.set_path(&format!("{}/", url.path()));
+        url
+    }
+}
+
+/// Build the URL for `tree`'s `path` endpoint under `base_url`, accounting
+/// for `Url::join`'s trailing-slash pitfall (see `with_trailing_slash`).
+fn tree_endpoint(base_url: &Url, tree: &str, path: &str) -> Result<Url> {
+    with_trailing_slash(base_url)
+        .join(&format!("{}/{}", tree, path))
+        .map_err(|e| {
+            ServerError::StickyProblem(ErrorDetails::new(ErrorLayer::ServerLayer, e.to_string()))
+        })
+}
+
+impl RemoteServer {
+    fn endpoint(&self, path: &str) -> Result<Url> {
+        tree_endpoint(&self.base_url, &self.tree, path)
+    }
+
+    /// `GET url`, turning a 404 response into a `StickyProblem` carrying the
+    /// URL rather than the status code, and any other non-2xx into a
+    /// `StickyProblem` via `reqwest::Error`'s `Display`.
+    async fn get(&self, url: Url) -> Result<reqwest::Response> {
+        let resp = self.client.get(url.clone()).send().await?;
+        if resp.status() == StatusCode::NOT_FOUND {
+            return Err(ServerError::StickyProblem(ErrorDetails::new(
+                ErrorLayer::BadInput,
+                format!("{}: not found", url),
+            )));
+        }
+        Ok(resp.error_for_status()?)
+    }
+}
+
+#[async_trait]
+impl AbstractServer for RemoteServer {
+    fn capabilities(&self) -> ServerCapabilities {
+        self.capabilities.clone()
+    }
+
+    fn translate_analysis_path(&self, sf_path: &str) -> Result<String> {
+        Ok(self.endpoint(&format!("analysis/{}", sf_path))?.to_string())
+    }
+
+    async fn fetch_raw_analysis(&self, sf_path: &str) -> Result<BoxStream<'static, Result<Value>>> {
+        let url = self.endpoint(&format!("analysis/{}", sf_path))?;
+        let resp = self.get(url.clone()).await?;
+        let byte_stream = resp
+            .bytes_stream()
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e));
+        let reader = StreamReader::new(byte_stream);
+        Ok(stream_ndjson_lines(reader, url.to_string()))
+    }
+
+    async fn fetch_html(&self, sf_path: &str) -> Result<String> {
+        let url = self.endpoint(&format!("source/{}", sf_path))?;
+        Ok(self.get(url).await?.text().await?)
+    }
+
+    async fn crossref_lookup(&self, symbol: &str) -> Result<Value> {
+        let url = self.endpoint(&format!("crossref/{}", symbol))?;
+        Ok(self.get(url).await?.json().await?)
+    }
+
+    async fn search_identifiers(
+        &self,
+        needle: &str,
+        exact_match: bool,
+        ignore_case: bool,
+        match_limit: usize,
+    ) -> Result<Vec<(String, String)>> {
+        let mut url = self.endpoint("search-identifiers")?;
+        url.query_pairs_mut()
+            .append_pair("q", needle)
+            .append_pair("exact", &exact_match.to_string())
+            .append_pair("ignore-case", &ignore_case.to_string())
+            .append_pair("limit", &match_limit.to_string());
+        Ok(self.get(url).await?.json().await?)
+    }
+
+    async fn perform_query(&self, q: &str) -> Result<Value> {
+        let mut url = self.endpoint("query")?;
+        url.query_pairs_mut().append_pair("q", q);
+        Ok(self.get(url).await?.json().await?)
+    }
+
+    async fn fetch_file_blame(&self, sf_path: &str, rev: &str) -> Result<Vec<BlameLine>> {
+        let mut url = self.endpoint(&format!("blame/{}", sf_path))?;
+        url.query_pairs_mut().append_pair("rev", rev);
+        Ok(self.get(url).await?.json().await?)
+    }
+
+    async fn fetch_file_at_rev(&self, sf_path: &str, rev: &str) -> Result<String> {
+        let mut url = self.endpoint(&format!("raw/{}", sf_path))?;
+        url.query_pairs_mut().append_pair("rev", rev);
+        Ok(self.get(url).await?.text().await?)
+    }
+}
+
+/// How long to wait for the `/version` handshake before giving up. A remote
+/// searchfox server that's merely slow shouldn't hang `build_pipeline`
+/// forever; a short, bounded timeout turns that into a transport error
+/// instead.
+const VERSION_FETCH_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Build a `RemoteServer` for `base_url`/`tree`, fetching the server's
+/// `/version` document up front to find out which protocol revision and
+/// capabilities it actually speaks.
+pub async fn make_remote_server(
+    base_url: Url,
+    tree: &str,
+) -> Result<Box<dyn AbstractServer + Send + Sync>> {
+    let client = reqwest::Client::new();
+    let version_url = tree_endpoint(&base_url, tree, "version")?;
+
+    Ok(Box::new(RemoteServer {
+        capabilities: fetch_capabilities(&client, &version_url).await?,
+        base_url,
+        tree: tree.to_string(),
+        client,
+    }))
+}
+
+/// Fetch and parse the remote server's `/version` document, using `client`
+/// (the same async `reqwest::Client` the rest of `RemoteServer` awaits its
+/// requests on -- a blocking call here would panic, since this runs inside
+/// the Tokio runtime `build_pipeline` executes under) with a bounded
+/// timeout so a wedged server doesn't hang construction indefinitely.
+///
+/// A 404 means an older server that predates this endpoint, which is
+/// treated as speaking protocol version 0 with no capabilities rather than
+/// failing construction outright -- `build_pipeline`'s capability check
+/// already turns "this server is missing capability X" into a clear
+/// "server too old" error for whichever command actually needs it. Any
+/// other failure -- DNS, connection refused, timeout, a non-404 error
+/// status, an unparseable body -- is a real transport problem, not
+/// evidence of an old server, and is surfaced as such rather than folded
+/// into the same "no capabilities" fallback.
+async fn fetch_capabilities(
+    client: &reqwest::Client,
+    version_url: &Url,
+) -> Result<ServerCapabilities> {
+    let resp = match client
+        .get(version_url.clone())
+        .timeout(VERSION_FETCH_TIMEOUT)
+        .send()
+        .await
+    {
+        Ok(resp) => resp,
+        Err(e) => return Err(ServerError::from(e)),
+    };
+
+    if resp.status() == StatusCode::NOT_FOUND {
+        return Ok(ServerCapabilities {
+            protocol_version: 0,
+            supported: vec![],
+        });
+    }
+
+    let resp = resp.error_for_status()?;
+    Ok(resp.json::<ServerCapabilities>().await?)
+}
@@ -0,0 +1,124 @@
+//! The `AbstractServer` trait: the interface every query backend (a local
+//! index, a remote searchfox web server, or a `MuxServer` fanning out across
+//! several of either) implements, so the pipeline machinery in
+//! `cmd_pipeline` never has to know which kind of backend it's talking to.
+
+use async_trait::async_trait;
+use futures_core::stream::BoxStream;
+use serde_json::Value;
+
+use super::blame::BlameLine;
+use super::capabilities::ServerCapabilities;
+
+/// Which layer of the system an error originated in, for clients that want
+/// to distinguish "you asked for something nonsensical" from "the server
+/// couldn't do it."
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorLayer {
+    BadInput,
+    ServerLayer,
+}
+
+/// Where in a source file an error occurred, for errors that can pin one
+/// down -- a line/byte-offset pair, most commonly a parse failure while
+/// streaming NDJSON records.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ErrorPosition {
+    pub line: u64,
+    pub byte_offset: u64,
+}
+
+/// The details carried by `ServerError::StickyProblem`: a human-readable
+/// message, which layer it came from, and -- when the error can be pinned
+/// to a specific spot in a source file -- the line/byte offset, so a
+/// JSON-format consumer can recover it without regexing `message`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ErrorDetails {
+    pub layer: ErrorLayer,
+    pub message: String,
+    pub position: Option<ErrorPosition>,
+}
+
+impl ErrorDetails {
+    pub fn new(layer: ErrorLayer, message: impl Into<String>) -> ErrorDetails {
+        ErrorDetails {
+            layer,
+            message: message.into(),
+            position: None,
+        }
+    }
+
+    pub fn with_position(
+        layer: ErrorLayer,
+        message: impl Into<String>,
+        position: ErrorPosition,
+    ) -> ErrorDetails {
+        ErrorDetails {
+            layer,
+            message: message.into(),
+            position: Some(position),
+        }
+    }
+}
+
+/// An error from an `AbstractServer` call.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ServerError {
+    /// A 404-shaped problem: the file/revision/symbol in question doesn't
+    /// exist, the input was malformed, or something else went wrong that
+    /// isn't going to un-happen on retry.
+    StickyProblem(ErrorDetails),
+    /// The backend doesn't implement this piece of functionality at all.
+    /// Distinct from `StickyProblem` so callers can tell "this backend can't
+    /// do it" apart from "this backend tried and failed." `MuxServer` treats
+    /// both alike, as a reason to try the next backend.
+    Unsupported,
+}
+
+pub type Result<T> = std::result::Result<T, ServerError>;
+
+/// The interface a query backend implements: fetching raw/HTML file
+/// content, symbol lookups, free-form queries, and git blame/history, plus
+/// reporting which of these it actually supports via `capabilities()`.
+#[async_trait]
+pub trait AbstractServer {
+    /// What this backend supports: the protocol version it speaks and which
+    /// individual capabilities it implements, so callers can check up front
+    /// instead of failing opaquely partway through a command.
+    fn capabilities(&self) -> ServerCapabilities;
+
+    /// Translate a searchfox path into the on-disk (or backend-specific)
+    /// path its raw analysis data lives at.
+    fn translate_analysis_path(&self, sf_path: &str) -> Result<String>;
+
+    /// Fetch `sf_path`'s raw analysis data as a lazy stream of parsed JSON
+    /// records. Each item is itself a `Result` because a parse failure on
+    /// one record (say, a corrupt line deep in the file) shouldn't force
+    /// the whole stream to be buffered up front just to check for it.
+    async fn fetch_raw_analysis(&self, sf_path: &str) -> Result<BoxStream<'static, Result<Value>>>;
+
+    /// Fetch the rendered HTML for `sf_path`.
+    async fn fetch_html(&self, sf_path: &str) -> Result<String>;
+
+    /// Look up a symbol's crossref data, or `Value::Null` if there is none.
+    async fn crossref_lookup(&self, symbol: &str) -> Result<Value>;
+
+    /// Search the identifiers index for `needle`, returning `(symbol, id)`
+    /// pairs.
+    async fn search_identifiers(
+        &self,
+        needle: &str,
+        exact_match: bool,
+        ignore_case: bool,
+        match_limit: usize,
+    ) -> Result<Vec<(String, String)>>;
+
+    /// Run a free-form query against the backend's search engine.
+    async fn perform_query(&self, q: &str) -> Result<Value>;
+
+    /// Blame `sf_path` line-by-line as of `rev`.
+    async fn fetch_file_blame(&self, sf_path: &str, rev: &str) -> Result<Vec<BlameLine>>;
+
+    /// Fetch the contents of `sf_path` as of `rev`.
+    async fn fetch_file_at_rev(&self, sf_path: &str, rev: &str) -> Result<String>;
+}
@@ -3,12 +3,14 @@ use url::Url;
 
 use crate::{
     abstract_server::{
-        make_local_server, make_remote_server, ErrorDetails, ErrorLayer, Result, ServerError,
+        make_local_server, make_remote_server, AbstractServer, Capability, ErrorDetails,
+        ErrorLayer, MuxServer, Result, ServerError,
     },
     cmd_pipeline::parser::{Command, OutputFormat, ToolOpts},
 };
 
 use super::{cmd_filter_analysis::FilterAnalysisCommand, cmd_merge_analyses::MergeAnalysesCommand, cmd_crossref_lookup::CrossrefLookupCommand, cmd_search_identifiers::SearchIdentifiersCommand};
+use super::cmd_blame::BlameCommand;
 use super::cmd_query::QueryCommand;
 use super::cmd_show_html::ShowHtmlCommand;
 
@@ -21,14 +23,14 @@ use super::interface::ServerPipeline;
 /// then break into separate sub-commands whenever we see a `|`.  We then pass
 /// these sub-commands to the structopt parsing `from_iter` method, taking care
 /// to stuff our binary name into the first arg.
-pub fn build_pipeline(bin_name: &str, arg_str: &str) -> Result<(ServerPipeline, OutputFormat)> {
+pub async fn build_pipeline(bin_name: &str, arg_str: &str) -> Result<(ServerPipeline, OutputFormat)> {
     let all_args = match shell_words::split(arg_str) {
         Ok(parsed) => parsed,
         Err(err) => {
-            return Err(ServerError::StickyProblem(ErrorDetails {
-                layer: ErrorLayer::BadInput,
-                message: err.to_string(),
-            }));
+            return Err(ServerError::StickyProblem(ErrorDetails::new(
+                ErrorLayer::BadInput,
+                err.to_string(),
+            )));
         }
     };
 
@@ -45,23 +47,36 @@ pub fn build_pipeline(bin_name: &str, arg_str: &str) -> Result<(ServerPipeline,
         let opts = match ToolOpts::from_iter_safe(fake_args) {
             Ok(opts) => opts,
             Err(err) => {
-                return Err(ServerError::StickyProblem(ErrorDetails {
-                    layer: ErrorLayer::BadInput,
-                    message: err.to_string(),
-                }));
+                return Err(ServerError::StickyProblem(ErrorDetails::new(
+                    ErrorLayer::BadInput,
+                    err.to_string(),
+                )));
             }
         };
         //println!("Pipeline segment: {:?}", opts);
 
         if first_time {
-            server = match Url::parse(&opts.server) {
-                Ok(url) => Some(make_remote_server(url, &opts.tree)?),
-                Err(_) => Some(make_local_server(&opts.server, &opts.tree)?),
-            };
+            server = Some(build_server(&opts.server, &opts.tree).await?);
             output_format = Some(opts.output_format);
             first_time = false;
         }
 
+        // Fail fast with a clear message instead of letting an unsupported
+        // command run partway before hitting a `ServerError::Unsupported`
+        // deep inside it.
+        if let Some(required) = required_capability(&opts.cmd) {
+            let capabilities = server.as_ref().unwrap().capabilities();
+            if !capabilities.supports(required) {
+                return Err(ServerError::StickyProblem(ErrorDetails::new(
+                    ErrorLayer::BadInput,
+                    format!(
+                        "server too old / feature {:?} unavailable (server speaks protocol v{}, this tool needs it)",
+                        required, capabilities.protocol_version
+                    ),
+                )));
+            }
+        }
+
         match opts.cmd {
             Command::CrossrefLookup(cl) => {
                 commands.push(Box::new(CrossrefLookupCommand { args: cl }))
@@ -90,6 +105,10 @@ pub fn build_pipeline(bin_name: &str, arg_str: &str) -> Result<(ServerPipeline,
             Command::ShowHtml(sh) => {
                 commands.push(Box::new(ShowHtmlCommand { args: sh }));
             }
+
+            Command::Blame(b) => {
+                commands.push(Box::new(BlameCommand { args: b }));
+            }
         }
     }
 
@@ -101,3 +120,55 @@ pub fn build_pipeline(bin_name: &str, arg_str: &str) -> Result<(ServerPipeline,
         output_format.unwrap(),
     ))
 }
+
+/// Build the backend for the first pipeline segment.  `server_spec` (and,
+/// in lockstep, `tree_spec`) may name a single `--server`/`--tree` pair, or
+/// several `+`-separated pairs (each optionally overriding the shared tree
+/// with its own `server,tree`) to search across multiple backends at once
+/// via a `MuxServer` -- e.g. `config.json+http://other-host/`.  A part that
+/// parses as a URL is a remote server; any other part is the config file
+/// path of a local index.
+async fn build_server(
+    server_spec: &str,
+    tree_spec: &str,
+) -> Result<Box<dyn AbstractServer + Send + Sync>> {
+    let server_parts: Vec<&str> = server_spec.split('+').collect();
+    let tree_parts: Vec<&str> = tree_spec.split('+').collect();
+
+    let mut backends = vec![];
+    for (i, part) in server_parts.iter().enumerate() {
+        let (server, tree) = match part.split_once(',') {
+            Some((server, tree)) => (server, tree),
+            None => (*part, *tree_parts.get(i).unwrap_or(&tree_spec)),
+        };
+
+        let backend = match Url::parse(server) {
+            Ok(url) => make_remote_server(url, tree).await,
+            Err(_) => make_local_server(server, tree),
+        }?;
+        backends.push(backend);
+    }
+
+    if backends.len() == 1 {
+        Ok(backends.pop().unwrap())
+    } else {
+        Ok(Box::new(MuxServer::new(backends)))
+    }
+}
+
+/// The `Capability` a command needs the server to support, if any.  Commands
+/// that only use server calls with no `Capability` of their own (such as
+/// `show-html`'s `fetch_html`) or that only post-process their input
+/// (filtering, merging) have no capability requirement.
+fn required_capability(cmd: &Command) -> Option<Capability> {
+    match cmd {
+        Command::CrossrefLookup(_) => Some(Capability::CrossrefLookup),
+        Command::SearchIdentifiers(_) => Some(Capability::SearchIdentifiers),
+        Command::Query(_) => Some(Capability::PerformQuery),
+        Command::Blame(_) => Some(Capability::Blame),
+        Command::FilterAnalysis(_)
+        | Command::MergeAnalyses(_)
+        | Command::ProductionFilter(_)
+        | Command::ShowHtml(_) => None,
+    }
+}
@@ -0,0 +1,42 @@
+use async_trait::async_trait;
+use serde_json::json;
+use structopt::StructOpt;
+
+use crate::abstract_server::{AbstractServer, Result};
+
+use super::interface::{PipelineCommand, PipelineValues};
+
+/// Annotate a file with git blame, so `show-html` output can show per-line
+/// revision/author/date without the Python web server needing to reach into
+/// the repository itself.
+#[derive(Debug, StructOpt)]
+pub struct Blame {
+    /// The searchfox path of the file to blame.
+    pub path: String,
+
+    /// The revision to blame as of; defaults to the tree's current HEAD.
+    #[structopt(short, long, default_value = "HEAD")]
+    pub rev: String,
+}
+
+pub struct BlameCommand {
+    pub args: Blame,
+}
+
+#[async_trait]
+impl PipelineCommand for BlameCommand {
+    async fn execute(
+        &self,
+        server: &(dyn AbstractServer + Send + Sync),
+        _input: PipelineValues,
+    ) -> Result<PipelineValues> {
+        let lines = server
+            .fetch_file_blame(&self.args.path, &self.args.rev)
+            .await?;
+
+        let records = lines.into_iter().map(|line| json!(line)).collect::<Vec<_>>();
+        Ok(PipelineValues::JsonRecords(Box::pin(
+            tokio_stream::iter(records),
+        )))
+    }
+}
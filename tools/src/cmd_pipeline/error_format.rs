@@ -0,0 +1,124 @@
+//! Render a `ServerError` the way the pipeline's selected `OutputFormat`
+//! asked for.
+//!
+//! Previously `ServerError`s always bubbled up as a raw human string, even
+//! when the caller asked for JSON output -- which makes it impossible for a
+//! tool wrapping the pipeline (say, a JSON-consuming frontend) to tell a
+//! structured error apart from stdout garbage.
+//!
+//! `build_pipeline` and the commands it builds always return a raw,
+//! unformatted `ServerError`. The pipeline runner (`ServerPipeline::run`)
+//! is the single call site that passes a command's failure through
+//! `format_error` with the requested `OutputFormat`, producing a tagged
+//! `{ "error": ... }` envelope for JSON or the plain message otherwise. A
+//! failure of `build_pipeline` itself never reaches the runner and is
+//! returned to its caller unformatted. Formatting anywhere upstream of that one
+//! call site would double-encode a JSON-format failure: the already-`json!`
+//! rendered string would get stuffed into a fresh error and re-rendered,
+//! turning the structured envelope into an escaped string inside another
+//! envelope.
+
+use serde_json::{json, Value};
+
+use crate::abstract_server::{ErrorLayer, ServerError};
+use crate::cmd_pipeline::parser::OutputFormat;
+
+fn layer_str(layer: &ErrorLayer) -> &'static str {
+    match layer {
+        ErrorLayer::BadInput => "bad-input",
+        ErrorLayer::ServerLayer => "server",
+    }
+}
+
+/// The plain human-readable message for an error, independent of output
+/// format.
+fn plain_message(err: &ServerError) -> String {
+    match err {
+        ServerError::StickyProblem(details) => details.message.clone(),
+        ServerError::Unsupported => "operation not supported by this server".to_string(),
+    }
+}
+
+/// The `{ "error": { "layer", "message", "kind" } }` envelope for a
+/// `ServerError`.
+fn to_json_envelope(err: &ServerError) -> Value {
+    match err {
+        ServerError::StickyProblem(details) => {
+            let mut envelope = json!({
+                "error": {
+                    "layer": layer_str(&details.layer),
+                    "message": details.message,
+                    "kind": "sticky",
+                }
+            });
+            if let Some(position) = &details.position {
+                envelope["error"]["line"] = json!(position.line);
+                envelope["error"]["byte_offset"] = json!(position.byte_offset);
+            }
+            envelope
+        }
+        ServerError::Unsupported => json!({
+            "error": {
+                "layer": Value::Null,
+                "message": plain_message(err),
+                "kind": "unsupported",
+            }
+        }),
+    }
+}
+
+/// Render `err` according to `output_format`: a structured JSON envelope
+/// when JSON output was requested, the plain message otherwise.
+pub fn format_error(err: &ServerError, output_format: &OutputFormat) -> String {
+    match output_format {
+        OutputFormat::Json => to_json_envelope(err).to_string(),
+        _ => plain_message(err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::abstract_server::{ErrorDetails, ErrorPosition};
+
+    use super::*;
+
+    #[test]
+    fn to_json_envelope_omits_position_fields_when_there_is_no_position() {
+        let err = ServerError::StickyProblem(ErrorDetails::new(ErrorLayer::BadInput, "bad"));
+        let envelope = to_json_envelope(&err);
+        assert_eq!(envelope["error"]["layer"], json!("bad-input"));
+        assert_eq!(envelope["error"]["message"], json!("bad"));
+        assert!(envelope["error"].get("line").is_none());
+        assert!(envelope["error"].get("byte_offset").is_none());
+    }
+
+    #[test]
+    fn to_json_envelope_includes_position_fields_when_present() {
+        let err = ServerError::StickyProblem(ErrorDetails::with_position(
+            ErrorLayer::ServerLayer,
+            "bad parse",
+            ErrorPosition {
+                line: 4,
+                byte_offset: 37,
+            },
+        ));
+        let envelope = to_json_envelope(&err);
+        assert_eq!(envelope["error"]["layer"], json!("server"));
+        assert_eq!(envelope["error"]["line"], json!(4));
+        assert_eq!(envelope["error"]["byte_offset"], json!(37));
+    }
+
+    #[test]
+    fn to_json_envelope_for_unsupported_has_a_null_layer_and_no_position() {
+        let envelope = to_json_envelope(&ServerError::Unsupported);
+        assert_eq!(envelope["error"]["kind"], json!("unsupported"));
+        assert_eq!(envelope["error"]["layer"], Value::Null);
+        assert!(envelope["error"].get("line").is_none());
+    }
+
+    #[test]
+    fn format_error_plain_ignores_structure_entirely() {
+        let err = ServerError::StickyProblem(ErrorDetails::new(ErrorLayer::BadInput, "bad"));
+        assert_eq!(format_error(&err, &OutputFormat::Pretty), "bad");
+    }
+}
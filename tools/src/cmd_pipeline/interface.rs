@@ -0,0 +1,88 @@
+//! The command/value plumbing `build_pipeline` assembles a `ServerPipeline`
+//! out of: each `PipelineCommand` takes the previous command's
+//! `PipelineValues` and the backend `AbstractServer`, and produces the next
+//! `PipelineValues`, so a `|`-separated pipeline segment chain is just
+//! `execute` calls threaded one into the next.
+
+use async_trait::async_trait;
+use futures_core::stream::BoxStream;
+use futures_util::StreamExt;
+use serde_json::Value;
+
+use crate::abstract_server::{AbstractServer, Result};
+
+use super::error_format::format_error;
+use super::parser::OutputFormat;
+
+/// What flows between pipeline segments: nothing yet (the first command in
+/// a pipeline), a lazy stream of JSON records, a single JSON value, or
+/// already-rendered text (`show-html`'s output, say).
+pub enum PipelineValues {
+    Void,
+    JsonRecords(BoxStream<'static, Value>),
+    Json(Value),
+    Text(String),
+}
+
+/// One segment of a pipeline: consumes the previous segment's output and
+/// produces the next one's, against whichever backend the pipeline was
+/// built against.
+#[async_trait]
+pub trait PipelineCommand {
+    async fn execute(
+        &self,
+        server: &(dyn AbstractServer + Send + Sync),
+        input: PipelineValues,
+    ) -> Result<PipelineValues>;
+}
+
+/// A fully built pipeline: the backend it talks to, and the sequence of
+/// commands to run against it.
+pub struct ServerPipeline {
+    pub server: Box<dyn AbstractServer + Send + Sync>,
+    pub commands: Vec<Box<dyn PipelineCommand>>,
+}
+
+impl ServerPipeline {
+    /// Run every command in sequence, each one's output feeding the next,
+    /// then render the final result -- or whichever command failed --
+    /// through `output_format`.  This is the "pipeline runner" `error_format`'s
+    /// module doc refers to, and the only place that calls `format_error`:
+    /// `build_pipeline` and the commands it builds only ever produce raw,
+    /// unformatted `ServerError`s.
+    pub async fn run(self, output_format: OutputFormat) -> String {
+        let server = self.server.as_ref();
+        let mut values = PipelineValues::Void;
+        for command in &self.commands {
+            values = match command.execute(server, values).await {
+                Ok(values) => values,
+                Err(err) => return format_error(&err, &output_format),
+            };
+        }
+        render_values(values, output_format).await
+    }
+}
+
+async fn render_values(values: PipelineValues, output_format: OutputFormat) -> String {
+    match values {
+        PipelineValues::Void => String::new(),
+        PipelineValues::Text(text) => text,
+        PipelineValues::Json(value) => render_json(&value, output_format),
+        PipelineValues::JsonRecords(mut stream) => {
+            let mut lines = vec![];
+            while let Some(value) = stream.next().await {
+                lines.push(render_json(&value, output_format));
+            }
+            lines.join("\n")
+        }
+    }
+}
+
+fn render_json(value: &Value, output_format: OutputFormat) -> String {
+    match output_format {
+        OutputFormat::Json => value.to_string(),
+        OutputFormat::Pretty => {
+            serde_json::to_string_pretty(value).unwrap_or_else(|_| value.to_string())
+        }
+    }
+}
@@ -0,0 +1,67 @@
+use std::str::FromStr;
+
+use structopt::StructOpt;
+
+use super::cmd_blame::Blame;
+use super::cmd_crossref_lookup::CrossrefLookup;
+use super::cmd_filter_analysis::FilterAnalysis;
+use super::cmd_merge_analyses::MergeAnalyses;
+use super::cmd_prod_filter::ProductionFilter;
+use super::cmd_query::Query;
+use super::cmd_search_identifiers::SearchIdentifiers;
+use super::cmd_show_html::ShowHtml;
+
+/// How the pipeline's final result (or a failure partway through) should be
+/// rendered on stdout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Json,
+    Pretty,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(OutputFormat::Json),
+            "pretty" => Ok(OutputFormat::Pretty),
+            other => Err(format!("unknown output format: {}", other)),
+        }
+    }
+}
+
+/// Which sub-command a single pipeline segment runs.
+#[derive(Debug, StructOpt)]
+pub enum Command {
+    CrossrefLookup(CrossrefLookup),
+    FilterAnalysis(FilterAnalysis),
+    MergeAnalyses(MergeAnalyses),
+    ProductionFilter(ProductionFilter),
+    Query(Query),
+    SearchIdentifiers(SearchIdentifiers),
+    ShowHtml(ShowHtml),
+    Blame(Blame),
+}
+
+/// The flags shared by every pipeline segment -- which server/tree to talk
+/// to and how to render the result -- plus the sub-command itself.
+#[derive(Debug, StructOpt)]
+pub struct ToolOpts {
+    /// `--server`: a config file path (local index) or a server URL, or a
+    /// `+`-separated list of such entries, each optionally `server,tree`, to
+    /// fan a query out across.
+    #[structopt(long)]
+    pub server: String,
+
+    /// `--tree`: the tree name(s) to pair with `--server`, `+`-separated in
+    /// lockstep with it.
+    #[structopt(long)]
+    pub tree: String,
+
+    #[structopt(long = "format", default_value = "pretty")]
+    pub output_format: OutputFormat,
+
+    #[structopt(subcommand)]
+    pub cmd: Command,
+}
@@ -0,0 +1,127 @@
+//! Compression-codec detection and transparent decoding for on-disk
+//! analysis/file artifacts.
+//!
+//! Artifacts may be stored gzip-, zstd-, or bzip2-compressed (or not
+//! compressed at all).  Rather than trust whatever extension a path happens
+//! to have, readers sniff the first few bytes of the file for the
+//! well-known magic numbers, so a single build can emit a mix of codecs and
+//! callers don't need to care which one a particular artifact used.
+
+use std::io;
+use std::path::Path;
+
+use async_compression::tokio::bufread::{BzDecoder, GzipDecoder, ZstdDecoder};
+use tokio::fs::File;
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+const BZIP2_MAGIC: [u8; 3] = [b'B', b'Z', b'h'];
+
+/// Compression codecs we know how to transparently decode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Codec {
+    Gzip,
+    Zstd,
+    Bzip2,
+    None,
+}
+
+/// Identify the codec a file is stored in by sniffing its leading bytes.
+fn sniff_codec(head: &[u8]) -> Codec {
+    if head.starts_with(&GZIP_MAGIC) {
+        Codec::Gzip
+    } else if head.starts_with(&ZSTD_MAGIC) {
+        Codec::Zstd
+    } else if head.starts_with(&BZIP2_MAGIC) {
+        Codec::Bzip2
+    } else {
+        Codec::None
+    }
+}
+
+/// Suffixes probed for a given base path, in priority order, alongside the
+/// extension-less fallback for uncompressed artifacts.
+const CODEC_SUFFIXES: [&str; 4] = [".gz", ".zst", ".bz2", ""];
+
+/// Given the uncompressed base path for an artifact (e.g.
+/// `{index}/analysis/foo/bar.cpp`), find whichever compressed (or
+/// uncompressed) variant actually exists on disk and return its path.
+pub fn probe_existing_path(base_path: &str) -> Option<String> {
+    for suffix in CODEC_SUFFIXES {
+        let candidate = format!("{}{}", base_path, suffix);
+        if Path::new(&candidate).is_file() {
+            return Some(candidate);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sniff_codec_recognizes_each_magic_number() {
+        assert_eq!(sniff_codec(&GZIP_MAGIC), Codec::Gzip);
+        assert_eq!(sniff_codec(&ZSTD_MAGIC), Codec::Zstd);
+        assert_eq!(sniff_codec(&BZIP2_MAGIC), Codec::Bzip2);
+    }
+
+    #[test]
+    fn sniff_codec_falls_back_to_none_on_unrecognized_or_short_input() {
+        assert_eq!(sniff_codec(b"plain text"), Codec::None);
+        assert_eq!(sniff_codec(b""), Codec::None);
+        // A prefix of a magic number isn't the magic number.
+        assert_eq!(sniff_codec(&GZIP_MAGIC[..1]), Codec::None);
+    }
+
+    #[test]
+    fn sniff_codec_only_looks_at_the_leading_bytes() {
+        // A gzip magic number anywhere but the start doesn't count.
+        let mut bytes = vec![0u8];
+        bytes.extend_from_slice(&GZIP_MAGIC);
+        assert_eq!(sniff_codec(&bytes), Codec::None);
+    }
+
+    #[test]
+    fn probe_existing_path_finds_the_first_matching_suffix_in_priority_order() {
+        let dir = std::env::temp_dir().join(format!(
+            "searchfox-codec-test-{}-{}",
+            std::process::id(),
+            line!()
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        let base = dir.join("analysis-foo").to_string_lossy().into_owned();
+
+        assert_eq!(probe_existing_path(&base), None);
+
+        std::fs::write(format!("{}.bz2", base), b"").unwrap();
+        std::fs::write(format!("{}.gz", base), b"").unwrap();
+        // `.gz` is earlier in CODEC_SUFFIXES than `.bz2`, so it wins even
+        // though `.bz2` was written first.
+        assert_eq!(probe_existing_path(&base), Some(format!("{}.gz", base)));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
+
+/// Open `path` and wrap it in whichever decoder matches its magic bytes,
+/// yielding a plain `AsyncRead` over the decompressed (or, for `Codec::None`,
+/// pass-through) contents.
+pub async fn open_decoded(path: &str) -> io::Result<Box<dyn AsyncRead + Send + Unpin>> {
+    let file = File::open(path).await?;
+    let mut reader = BufReader::new(file);
+
+    // Peek at the header without consuming it so whichever decoder we pick
+    // still sees the stream from the start.
+    let head = reader.fill_buf().await?;
+    let codec = sniff_codec(head);
+
+    Ok(match codec {
+        Codec::Gzip => Box::new(GzipDecoder::new(reader)),
+        Codec::Zstd => Box::new(ZstdDecoder::new(reader)),
+        Codec::Bzip2 => Box::new(BzDecoder::new(reader)),
+        Codec::None => Box::new(reader),
+    })
+}